@@ -3,22 +3,46 @@
 //
 // A command line game: Towers of Hanoi
 
-use std::{env,io};
+extern crate rustyline;
+
+use std::env;
+use std::collections::HashMap;
 use std::fmt::Write;
 use std::str::FromStr;
 
+use rustyline::Editor;
+use rustyline::error::ReadlineError;
+
+/// The file interactive line history is loaded from and saved to between runs.
+const HISTORY_FILE: &str = ".hanoi_history";
+
 /// A single disk, identified by its size.
 #[derive(PartialEq,Eq,PartialOrd,Ord,Clone,Copy,Debug)]
 struct Disk(u8);
 
 const START_SIZE: u8 = 3;
+const DEFAULT_PEGS: usize = 3;
+const MIN_PEGS: usize = 3;
+// `peg_letter` maps pegs onto 'a'..='z'; past that, `b'a' + peg.0 as u8` overflows a `u8`.
+const MAX_PEGS: usize = 26;
+// `solve` materializes every move of the solution into a `Vec<Move>` before playing it out, and
+// the worst case (3 pegs) needs `2^n - 1` of them; 20 keeps that in the low millions instead of
+// the billions (or, past `n == 64`, an overflow panic in `frame_stewart_count`'s shift), so it
+// actually finishes instead of exhausting memory.
+const MAX_DISKS: u8 = 20;
 
 /// The state of the game, represented by a vector of `Disk`s on each peg.
 /// The bottom of each peg is the front of each vector.
 struct State {
-    left: Vec<Disk>,
-    center: Vec<Disk>,
-    right: Vec<Disk>,
+    pegs: Vec<Vec<Disk>>,
+    /// The number of disks the game started with, used to compute the optimal move count.
+    total_disks: u8,
+    /// The number of moves the player has made so far.
+    moves: u32,
+    /// Moves applied so far, in order, for `undo` to walk back through.
+    history: Vec<Move>,
+    /// Moves popped off `history` by `undo`, in order, for `redo` to replay.
+    redo_stack: Vec<Move>,
 }
 
 /// A move operation from one peg to another. Note: the move may not actually be allowed!
@@ -30,14 +54,17 @@ struct Move {
 
 impl Move {
     fn new(from: Peg, to: Peg) -> Move {
-        unimplemented!()
+        Move { from, to }
     }
 }
 
-/// An indentifier for a peg
+/// An identifier for a peg, by its index into `State::pegs`.
 #[derive(PartialEq,Eq,Clone,Copy,Debug)]
-enum Peg {
-    Left, Center, Right
+struct Peg(usize);
+
+/// The letter players use to refer to `peg` (`Peg(0)` is `'a'`, `Peg(1)` is `'b'`, ...).
+fn peg_letter(peg: Peg) -> char {
+    (b'a' + peg.0 as u8) as char
 }
 
 /// An action inputted by the user
@@ -45,6 +72,12 @@ enum Peg {
 enum Action {
     /// Do this move
     Move(Move),
+    /// Undo the last move
+    Undo,
+    /// Redo the last undone move
+    Redo,
+    /// Play out the optimal solution from here
+    Solve,
     /// Quit the game
     Quit,
 }
@@ -56,6 +89,10 @@ enum NextStep {
     Quit,
     /// The user won -- congratulate them!
     Win,
+    /// A move was undone
+    Undone,
+    /// An undone move was redone
+    Redone,
     /// Get another action from the user
     Continue,
 }
@@ -68,53 +105,268 @@ enum HanoiError {
     UnstableStack(Peg, Disk),
     /// You can't move from `Peg` because it's empty
     EmptyFrom(Peg),
+    /// There's no move left to undo
+    NothingToUndo,
+    /// There's no undone move left to redo
+    NothingToRedo,
+    /// `solve` was asked to move more than one disk with fewer than 3 pegs, which is impossible
+    NotEnoughPegs,
+    /// `solve` only knows how to play from a single clean stack; the disks aren't arranged that
+    /// way right now.
+    NotStacked,
 }
 
 impl HanoiError {
     fn description(&self) -> String {
         match *self {
-            HanoiError::UnknownCommand => format!("Unknown Command"),
-            HanoiError::UnstableStack(peg, Disk(size)) => unimplemented!(),
-            HanoiError::EmptyFrom(peg) => unimplemented!(),
+            HanoiError::UnknownCommand => "Unknown Command".to_string(),
+            HanoiError::UnstableStack(peg, Disk(size)) =>
+                format!("Can't place disk {} on peg {}, it's bigger than the top disk there", size, peg_letter(peg)),
+            HanoiError::EmptyFrom(peg) => format!("Can't move from peg {}, it's empty", peg_letter(peg)),
+            HanoiError::NothingToUndo => "No moves to undo".to_string(),
+            HanoiError::NothingToRedo => "No undone moves to redo".to_string(),
+            HanoiError::NotEnoughPegs => "Need at least 3 pegs to solve".to_string(),
+            HanoiError::NotStacked => "All disks must be stacked cleanly on one peg to solve".to_string(),
         }
     }
 }
 
+/// Turns `c` into the `Peg` it names, if any. `num_pegs` bounds which letters are in play.
+fn peg_from_char(c: char, num_pegs: usize) -> Option<Peg> {
+    let index = (c as u32).wrapping_sub('a' as u32) as usize;
+    if index < num_pegs {
+        Some(Peg(index))
+    } else {
+        None
+    }
+}
+
+/// Scans the next whitespace-separated token off the front of `input`.
+///
+/// ## Returns
+///
+/// `Some((token, rest))` where `token` is the next non-space chunk and `rest` is everything
+/// after it (including its leading whitespace, if any), or `None` if `input` is all whitespace.
+fn next_token(input: &str) -> Option<(&str, &str)> {
+    let input = input.trim_start();
+    if input.is_empty() {
+        return None;
+    }
+    match input.find(char::is_whitespace) {
+        Some(end) => Some((&input[..end], &input[end..])),
+        None => Some((input, "")),
+    }
+}
 
-/// Parses the input into a [potential] use action.
+/// Parses a single command token into an action.
 ///
 /// Acceptable commands:
 ///    * `q`: Quit
-///    * `PQ`: Move the top disk from P into Q, where P, Q are in ['l', 'c', 'r']
+///    * `s`: Solve -- play out the optimal solution from here
+///    * `u`: Undo the last move
+///    * `y`: Redo the last undone move
+///    * `PQ`: Move the top disk from P into Q, where P, Q are peg letters starting at 'a'
 ///
 /// ## Returns
 ///
-///    * `Action`: if the input was well formed
+///    * `Action`: if the token was well formed
 ///    * `Hanoi::UnknownCommand`: otherwise
-fn parse_action(input: &str) -> Result<Action,HanoiError> {
-    unimplemented!()
+fn parse_single_action(token: &str, num_pegs: usize) -> Result<Action,HanoiError> {
+    match token {
+        "q" => return Ok(Action::Quit),
+        "s" => return Ok(Action::Solve),
+        "u" => return Ok(Action::Undo),
+        "y" => return Ok(Action::Redo),
+        _ => (),
+    }
+
+    let mut chars = token.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some(from), Some(to), None) => {
+            match (peg_from_char(from, num_pegs), peg_from_char(to, num_pegs)) {
+                (Some(from), Some(to)) => Ok(Action::Move(Move::new(from, to))),
+                _ => Err(HanoiError::UnknownCommand),
+            }
+        }
+        _ => Err(HanoiError::UnknownCommand),
+    }
+}
+
+/// Parses a whitespace-separated sequence of commands (e.g. `"lc cr lr"`) into the actions
+/// they name, in order. This lets players paste a whole solution or script several moves at
+/// once; a single command is just the common case of a one-token sequence.
+///
+/// ## Returns
+///
+///    * `Vec<Action>`: the parsed actions, if every token was well formed
+///    * `Hanoi::UnknownCommand`: if any token wasn't
+fn parse_action(input: &str, num_pegs: usize) -> Result<Vec<Action>,HanoiError> {
+    let mut actions = Vec::new();
+    let mut rest = input;
+    while let Some((token, remainder)) = next_token(rest) {
+        actions.push(parse_single_action(token, num_pegs)?);
+        rest = remainder;
+    }
+    Ok(actions)
+}
+
+/// Runs `actions` against `state` in order, stopping at the first error, `Quit`, or `Win`, and
+/// printing the board between steps so a batch of moves plays out like a sequence of single
+/// commands would.
+fn run_actions(state: &mut State, actions: Vec<Action>) -> Result<NextStep, HanoiError> {
+    let total = actions.len();
+    let mut next_step = NextStep::Continue;
+    for (i, action) in actions.into_iter().enumerate() {
+        next_step = match action {
+            Action::Quit => NextStep::Quit,
+            Action::Move(mov) => state.apply_move(mov)?,
+            Action::Undo => state.undo()?,
+            Action::Redo => state.redo()?,
+            Action::Solve => state.solve()?,
+        };
+        if next_step == NextStep::Quit || next_step == NextStep::Win {
+            break;
+        }
+        if i + 1 < total {
+            state.print();
+        }
+    }
+    Ok(next_step)
+}
+
+/// Appends the moves of the classic recursive 3-peg solution for moving `n` disks from `src` to
+/// `dst` (using `aux` as the spare peg) onto `moves`.
+fn hanoi_moves(n: u8, src: Peg, dst: Peg, aux: Peg, moves: &mut Vec<Move>) {
+    if n == 0 {
+        return;
+    }
+    hanoi_moves(n - 1, src, aux, dst, moves);
+    moves.push(Move::new(src, dst));
+    hanoi_moves(n - 1, aux, dst, src, moves);
+}
+
+/// The minimum number of moves Frame-Stewart needs to move `n` disks using `num_pegs` pegs.
+/// `u64::MAX` stands in for "impossible" (fewer than 3 pegs can't move more than one
+/// disk). Memoized on `(n, num_pegs)` since the recurrence branches widely.
+fn frame_stewart_count(n: u8, num_pegs: usize, memo: &mut HashMap<(u8, usize), u64>) -> u64 {
+    if n == 0 {
+        return 0;
+    }
+    if n == 1 {
+        return if num_pegs >= 2 { 1 } else { u64::MAX };
+    }
+    if num_pegs == 2 {
+        // Two pegs can only ever move a single disk.
+        return u64::MAX;
+    }
+    if num_pegs == 3 {
+        return (1u64 << n) - 1;
+    }
+    if num_pegs < 2 {
+        return u64::MAX;
+    }
+    if let Some(&cached) = memo.get(&(n, num_pegs)) {
+        return cached;
+    }
+
+    let mut best = u64::MAX;
+    for k in 1..n {
+        let move_k_twice = frame_stewart_count(k, num_pegs, memo);
+        let move_rest = frame_stewart_count(n - k, num_pegs - 1, memo);
+        if move_k_twice == u64::MAX || move_rest == u64::MAX {
+            continue;
+        }
+        let total = 2 * move_k_twice + move_rest;
+        if total < best {
+            best = total;
+        }
+    }
+    memo.insert((n, num_pegs), best);
+    best
+}
+
+/// Appends the moves of the Frame-Stewart solution for moving `n` disks from `src` to `dst`,
+/// using the remaining entries of `pegs` (which must include `src` and `dst`) as spares, onto
+/// `moves`. With exactly 3 available pegs this is the classic solution.
+fn frame_stewart_moves(n: u8, src: Peg, dst: Peg, pegs: &[Peg], memo: &mut HashMap<(u8, usize), u64>, moves: &mut Vec<Move>) {
+    if n == 0 {
+        return;
+    }
+    if n == 1 {
+        moves.push(Move::new(src, dst));
+        return;
+    }
+    if pegs.len() == 3 {
+        let aux = pegs.iter().cloned().find(|&p| p != src && p != dst)
+            .expect("frame_stewart_moves requires >= 3 pegs, enforced by its caller");
+        hanoi_moves(n, src, dst, aux, moves);
+        return;
+    }
+
+    let mut best_k = 1;
+    let mut best_total = u64::MAX;
+    for k in 1..n {
+        let move_k_twice = frame_stewart_count(k, pegs.len(), memo);
+        let move_rest = frame_stewart_count(n - k, pegs.len() - 1, memo);
+        if move_k_twice == u64::MAX || move_rest == u64::MAX {
+            continue;
+        }
+        let total = 2 * move_k_twice + move_rest;
+        if total < best_total {
+            best_total = total;
+            best_k = k;
+        }
+    }
+
+    let tmp = pegs.iter().cloned().find(|&p| p != src && p != dst)
+        .expect("frame_stewart_moves requires >= 3 pegs, enforced by its caller");
+    let without_tmp: Vec<Peg> = pegs.iter().cloned().filter(|&p| p != tmp).collect();
+
+    frame_stewart_moves(best_k, src, tmp, pegs, memo, moves);
+    frame_stewart_moves(n - best_k, src, dst, &without_tmp, memo, moves);
+    frame_stewart_moves(best_k, tmp, dst, pegs, memo, moves);
 }
 
 impl State {
 
-    /// Creates a Towers of Hanoi game with `disks` disks in a single tower
-    fn new(disks: u8) -> State {
-        unimplemented!()
+    /// Creates a Towers of Hanoi game with `disks` disks in a single tower, spread across
+    /// `num_pegs` pegs.
+    fn new(disks: u8, num_pegs: usize) -> State {
+        let mut pegs = vec![Vec::new(); num_pegs];
+        pegs[0] = (1..disks + 1).rev().map(Disk).collect();
+        State {
+            pegs,
+            total_disks: disks,
+            moves: 0,
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+        }
+    }
+
+    /// How many pegs are in play.
+    fn num_pegs(&self) -> usize {
+        self.pegs.len()
+    }
+
+    /// The minimum number of moves required to solve the game from a fresh start, per
+    /// Frame-Stewart.
+    fn optimal_moves(&self) -> u64 {
+        frame_stewart_count(self.total_disks, self.num_pegs(), &mut HashMap::new())
     }
 
     /// Mutably borrow the tower for `peg`
     fn get_tower_mut(&mut self, peg: Peg) -> &mut Vec<Disk> {
-        unimplemented!()
+        &mut self.pegs[peg.0]
     }
 
     /// Immutably borrow the tower for `peg`
     fn get_tower(&self, peg: Peg) -> &Vec<Disk> {
-        unimplemented!()
+        &self.pegs[peg.0]
     }
 
     /// Pop the top disk off `peg`, if possible
     fn pop_disk(&mut self, peg: Peg) -> Option<Disk> {
-        unimplemented!()
+        self.get_tower_mut(peg).pop()
     }
 
     /// Get a copy of the top disk on `peg`, if possible
@@ -131,12 +383,20 @@ impl State {
     /// `HanoiError::UnstableStack` if this operation attempted to put `disk` on a smaller
     /// disk.
     fn push_disk(&mut self, peg: Peg, disk: Disk) -> Result<(), HanoiError> {
-        unimplemented!()
+        if let Some(top) = self.peek_disk(peg) {
+            if disk > top {
+                return Err(HanoiError::UnstableStack(peg, disk));
+            }
+        }
+        self.get_tower_mut(peg).push(disk);
+        Ok(())
     }
 
-    /// Returns true if the game has been won!
+    /// Returns true if the game has been won! The goal is always to gather every disk onto the
+    /// last peg.
     fn done(&self) -> bool {
-        unimplemented!()
+        let last = self.num_pegs() - 1;
+        self.pegs.iter().enumerate().all(|(i, tower)| i == last || tower.is_empty())
     }
 
     /// Executes the given move.
@@ -149,7 +409,89 @@ impl State {
     ///
     /// No change is made to `self` if an error occurs.
     fn do_move(&mut self, mov: Move) -> Result<NextStep, HanoiError> {
-        unimplemented!()
+        let disk = self.pop_disk(mov.from).ok_or(HanoiError::EmptyFrom(mov.from))?;
+
+        if let Err(err) = self.push_disk(mov.to, disk) {
+            // Undo the pop so a failed move leaves `self` untouched.
+            self.get_tower_mut(mov.from).push(disk);
+            return Err(err);
+        }
+        self.moves += 1;
+        self.history.push(mov);
+
+        if self.done() {
+            Ok(NextStep::Win)
+        } else {
+            Ok(NextStep::Continue)
+        }
+    }
+
+    /// Executes `mov` as a fresh move, clearing `redo_stack` since it no longer applies once the
+    /// player has branched off in a new direction.
+    fn apply_move(&mut self, mov: Move) -> Result<NextStep, HanoiError> {
+        let next_step = self.do_move(mov)?;
+        self.redo_stack.clear();
+        Ok(next_step)
+    }
+
+    /// Undoes the last applied move, if any.
+    ///
+    /// This reverses the move directly rather than going through `push_disk`, since a move
+    /// that was previously applied is guaranteed to be valid to reverse.
+    fn undo(&mut self) -> Result<NextStep, HanoiError> {
+        let mov = self.history.pop().ok_or(HanoiError::NothingToUndo)?;
+        let disk = self.get_tower_mut(mov.to).pop()
+            .expect("a move in `history` should still have its disk on `to`");
+        self.get_tower_mut(mov.from).push(disk);
+        self.moves -= 1;
+        self.redo_stack.push(mov);
+        Ok(NextStep::Undone)
+    }
+
+    /// Re-applies the last move undone by `undo`, if any.
+    fn redo(&mut self) -> Result<NextStep, HanoiError> {
+        let mov = self.redo_stack.pop().ok_or(HanoiError::NothingToRedo)?;
+        match self.do_move(mov)? {
+            NextStep::Win => Ok(NextStep::Win),
+            _ => Ok(NextStep::Redone),
+        }
+    }
+
+    /// Computes the Frame-Stewart-optimal solution for the current configuration and plays it
+    /// out move by move through `do_move`, printing the board after each step.
+    ///
+    /// The source peg is derived as whichever peg currently holds every disk, stacked cleanly in
+    /// descending order, so this also works mid-game as long as the player hasn't left disks
+    /// scattered across more than one peg. That precondition is validated up front, before any
+    /// move is applied, so a `solve` that can't proceed is a clean no-op error rather than a
+    /// partial scramble. The destination is always the last peg, matching `done`.
+    fn solve(&mut self) -> Result<NextStep, HanoiError> {
+        let total = self.total_disks;
+        let all_pegs: Vec<Peg> = (0..self.num_pegs()).map(Peg).collect();
+
+        if frame_stewart_count(total, all_pegs.len(), &mut HashMap::new()) == u64::MAX {
+            return Err(HanoiError::NotEnoughPegs);
+        }
+
+        let cleanly_stacked: Vec<Disk> = (1..=total).rev().map(Disk).collect();
+        let src = all_pegs.iter().cloned()
+            .find(|&peg| *self.get_tower(peg) == cleanly_stacked)
+            .ok_or(HanoiError::NotStacked)?;
+        let dst = Peg(self.num_pegs() - 1);
+
+        let mut moves = Vec::new();
+        let mut memo = HashMap::new();
+        frame_stewart_moves(total, src, dst, &all_pegs, &mut memo, &mut moves);
+
+        let mut next_step = NextStep::Continue;
+        for mov in moves {
+            next_step = self.apply_move(mov)?;
+            self.print();
+            if next_step == NextStep::Win {
+                break;
+            }
+        }
+        Ok(next_step)
     }
 
     /// Prints the contents of `peg` to stdout
@@ -157,47 +499,70 @@ impl State {
 
         // Make a string of disk sizes
         let mut string = String::new();
-        for &Disk(ref size) in self.get_tower(peg) {
+        for Disk(size) in self.get_tower(peg) {
             // Write the size onto the string, `unwrap` will never panic here because writing onto
             // a String is gauranteed to succeed.
             write!(string, "{} ", size).unwrap();
         }
         string.pop(); // Pop off the trailing space.
 
-        let peg_name = match peg {
-            Peg::Left   => "  Left",
-            Peg::Center => "Center",
-            Peg::Right  => " Right",
-        };
-
-        println!("{}: {}", peg_name, string);
+        println!("Peg {}: {}", peg_letter(peg), string);
     }
 
     /// Prints the state of the game to stdout
     fn print(&self) {
-        self.print_peg(Peg::Left);
-        self.print_peg(Peg::Center);
-        self.print_peg(Peg::Right);
+        for i in 0..self.num_pegs() {
+            self.print_peg(Peg(i));
+        }
     }
 }
 
 fn main() {
-    // Reads the first command line arguments and parses it an integer.
-    // `None` if no argument was provided or if the parse failed.
-    let user_start_size = env::args().nth(1).and_then(|arg| u8::from_str(arg.as_str()).ok());
-    let mut state = State::new(user_start_size.unwrap_or(START_SIZE));
+    // Reads the first two command line arguments: disk count and peg count.
+    // `None` if an argument wasn't provided or its parse failed.
+    let mut args = env::args();
+    args.next();
+    let user_start_size = args.next().and_then(|arg| u8::from_str(arg.as_str()).ok());
+    let user_num_pegs = args.next().and_then(|arg| usize::from_str(arg.as_str()).ok());
+
+    // Fewer than 3 pegs can't host a game at all (`State::new` indexes `pegs[0]`), and more than
+    // 26 has no letter left to name it (`peg_letter` overflows past 'z'), so an invalid count
+    // falls back to the default just like an unparseable one does.
+    let num_pegs = user_num_pegs.filter(|&n| (MIN_PEGS..=MAX_PEGS).contains(&n)).unwrap_or(DEFAULT_PEGS);
+    // A disk count above MAX_DISKS would overflow frame_stewart_count's move-count arithmetic
+    // and ask hanoi_moves to materialize an unplayable number of moves, so it falls back to the
+    // default too.
+    let start_size = user_start_size.filter(|&n| (1..=MAX_DISKS).contains(&n)).unwrap_or(START_SIZE);
+    let mut state = State::new(start_size, num_pegs);
+
+    // Line editor with persistent history, so players get arrow-key editing and can recall
+    // commands from previous runs.
+    let mut editor = Editor::<()>::new();
+    let _ = editor.load_history(HISTORY_FILE);
 
-    // We'll read input into here.
-    let mut line = String::new();
     loop {
         state.print();
+
         // Get input
-        io::stdin().read_line(&mut line).unwrap();
+        let input = match editor.readline("> ") {
+            Ok(line) => {
+                editor.add_history_entry(line.as_str());
+                line
+            }
+            // Ctrl-D: treat it as an implicit Quit.
+            Err(ReadlineError::Eof) => {
+                println!("Quitting");
+                break;
+            }
+            Err(err) => {
+                println!("Error reading input: {}", err);
+                break;
+            }
+        };
 
-        // Parse and perform action
-        let next_step_or_err = parse_action(line.as_str().trim()).and_then(|action| {
-            unimplemented!()
-        });
+        // Parse and perform the (possibly several) actions on the line
+        let next_step_or_err = parse_action(input.trim(), num_pegs)
+            .and_then(|actions| run_actions(&mut state, actions));
 
         // Handle the next step
         match next_step_or_err {
@@ -207,14 +572,51 @@ fn main() {
             }
             Ok(NextStep::Win) => {
                 state.print();
-                println!("You won!");
+                let optimal = state.optimal_moves();
+                println!("You won in {} moves! The optimal solution takes {} moves ({:.0}% efficient).",
+                    state.moves, optimal, 100.0 * optimal as f64 / state.moves as f64);
                 break;
             }
+            Ok(NextStep::Undone) => println!("Move undone."),
+            Ok(NextStep::Redone) => println!("Move redone."),
             Ok(NextStep::Continue) => (),
             Err(err) => println!("Error: {}", err.description()),
         }
+    }
+
+    let _ = editor.save_history(HISTORY_FILE);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_stewart_count_matches_known_values() {
+        assert_eq!(frame_stewart_count(6, 4, &mut HashMap::new()), 17);
+        assert_eq!(frame_stewart_count(10, 4, &mut HashMap::new()), 49);
+    }
+
+    #[test]
+    fn solve_wins_from_a_clean_start() {
+        let mut state = State::new(4, 3);
+        assert_eq!(state.solve(), Ok(NextStep::Win));
+        assert!(state.done());
+    }
+
+    #[test]
+    fn solve_rejects_a_scattered_board() {
+        let mut state = State::new(4, 3);
+        state.apply_move(Move::new(Peg(0), Peg(1))).unwrap();
+        assert_eq!(state.solve(), Err(HanoiError::NotStacked));
+    }
 
-        // Make space for future input
-        line.clear();
+    #[test]
+    fn undo_reverses_do_move() {
+        let mut state = State::new(3, 3);
+        let before = state.pegs.clone();
+        state.apply_move(Move::new(Peg(0), Peg(2))).unwrap();
+        state.undo().unwrap();
+        assert_eq!(state.pegs, before);
     }
 }